@@ -25,6 +25,53 @@ fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&DirEntry)) -> io::Result<()> {
     Ok(())
 }
 
+// Builds the `{expectation}` block spliced into a generated test: loads
+// `gold`, compares it against `fresh` (computed from that test's own
+// `stdout`/`result`), and either asserts equality or, with `allow_write`,
+// rewrites `gold` under `UPDATE_EXPECT=1`. Only the primary interpreter
+// test should pass `allow_write: true` — the `_matches_cpp_backend` test
+// reuses the same `gold` path but has its own `fresh` (a compiled binary
+// has no trailing interpreter `result`), so if both tests could write
+// under `UPDATE_EXPECT=1` they'd race on the same file with different
+// contents. The cpp-diff test gets the read-only variant so it still
+// fails loudly on a mismatch without ever touching the file.
+fn golden_file_check(gold: &str, allow_write: bool) -> String {
+    if allow_write {
+        format!("
+    eprintln!(\"Loading golden file {{}}\", \"{gold}\");
+    let fresh = format!(\"{{}}{{}}\", stdout.join(\"\"), result);
+    if std::env::var(\"UPDATE_EXPECT\").as_deref() == Ok(\"1\") {{
+        let mut golden = String::new();
+        if let Ok(mut goldfile) = std::fs::File::open(\"{gold}\") {{
+            goldfile.read_to_string(&mut golden).unwrap();
+        }}
+        if golden.replace(\"\\r\n\", \"\n\") != fresh {{
+            eprintln!(\"Updating golden file {{}}:\\n--- old\\n{{}}\\n--- new\\n{{}}\", \"{gold}\", golden, fresh);
+            std::fs::write(\"{gold}\", &fresh).expect(\"failed to write golden file\");
+        }}
+    }} else {{
+        let mut goldfile=std::fs::File::open(\"{gold}\").unwrap();
+        let mut golden = String::new();
+        goldfile.read_to_string(&mut golden).unwrap();
+        eprintln!(\"DONE 3\");
+        use pretty_assertions::assert_eq;
+        assert_eq!(golden.replace(\"\\r\n\", \"\n\"), fresh);
+    }}",
+        gold = gold)
+    } else {
+        format!("
+    eprintln!(\"Loading golden file {{}}\", \"{gold}\");
+    let fresh = format!(\"{{}}{{}}\", stdout.join(\"\"), result);
+    let mut goldfile=std::fs::File::open(\"{gold}\").unwrap();
+    let mut golden = String::new();
+    goldfile.read_to_string(&mut golden).unwrap();
+    eprintln!(\"DONE 3\");
+    use pretty_assertions::assert_eq;
+    assert_eq!(golden.replace(\"\\r\n\", \"\n\"), fresh);",
+        gold = gold)
+    }
+}
+
 fn build_test(mut f: &std::fs::File, path: String) {
     let mut test = String::new();
     let mut file = std::fs::File::open(path.to_string()).unwrap();
@@ -32,20 +79,16 @@ fn build_test(mut f: &std::fs::File, path: String) {
 
     eprintln!("Building test '{}'", path);
     let opts = TestOptions::from_str(&test).expect("Couldn't read test options");
-    let (test_type, expectation) = if opts.expected == TestResult::Panic {
-        ("\n#[should_panic]", "".to_owned()) // No result checking needed.
+    let (test_type, expectation, expectation_readonly) = if opts.expected == TestResult::Panic {
+        ("\n#[should_panic]", "".to_owned(), "".to_owned()) // No result checking needed.
     } else if let TestResult::Output(gold) = opts.expected {
-        ("", format!("
-    eprintln!(\"Loading golden file {{}}\", \"{gold}\");
-    let mut goldfile=std::fs::File::open(\"{gold}\").unwrap();
-    let mut golden = String::new();
-    goldfile.read_to_string(&mut golden).unwrap();
-    eprintln!(\"DONE 3\");
-    use pretty_assertions::assert_eq;
-    assert_eq!(golden.replace(\"\\r\n\", \"\n\"), format!(\"{{}}{{}}\", stdout.join(\"\"), result));",
-    gold = gold))
+        (
+            "",
+            golden_file_check(&gold, true),
+            golden_file_check(&gold, false),
+        )
     } else {
-        ("", "".to_owned())
+        ("", "".to_owned(), "".to_owned())
     };
 
     let fn_name = path.replace("/", "_").replace("\\", "_").replace("._", "");
@@ -76,15 +119,131 @@ fn {fn_name}() {{
         {expectation}
         eprintln!(\"DONE 4\");
     }}
-}}",
+}}{cpp_diff_test}{llvm_smoke_test}",
         fn_name = fn_name,
         test_type = test_type,
         opts = test,
-        expectation = expectation
+        expectation = expectation,
+        cpp_diff_test = build_cpp_diff_test(&fn_name, &test, &expectation_readonly),
+        llvm_smoke_test = build_llvm_smoke_test(&fn_name, &test),
     )
     .unwrap();
 }
 
+// Emits a second `#[test]` per example that compiles the same module with the
+// C++ backend, runs the resulting binary, and checks its stdout against the
+// golden file (via `{expectation}`, re-run against the compiled binary's
+// `stdout`/`result`). Always takes the read-only `expectation_readonly` from
+// `build_test`, never the write-capable one: this test and the primary
+// interpreter test would otherwise both try to update the same golden file
+// under `UPDATE_EXPECT=1`, racing with different `fresh` values. Skipped
+// unless `TAKO_ENABLE_CPP_TESTS=1` is set, since CI/dev machines without a
+// system C++ compiler shouldn't fail the suite over it.
+fn build_cpp_diff_test(fn_name: &str, opts: &str, expectation: &str) -> String {
+    if std::env::var("TAKO_ENABLE_CPP_TESTS").as_deref() != Ok("1") {
+        return "".to_string();
+    }
+    format!(
+        "
+#[test]
+fn {fn_name}_matches_cpp_backend() {{
+    let topts = TestOptions::from_str(\"{opts}\").expect(\"Couldn't read test options\");
+    let opts = topts.opts;
+    let mut db = DB::default();
+    db.set_options(opts);
+    for f in db.options().files.iter() {{
+        let module = db.module_name(f.to_owned());
+        let (cpp_source, flags) = crate::to_cpp::generate(
+            crate::to_cpp::Backend::Cpp,
+            &db,
+            &module,
+            &crate::externs::ExternRegistry::default(),
+        ).expect(\"codegen failed\");
+
+        let dir = std::env::temp_dir();
+        let src_path = dir.join(format!(\"{{}}.cc\", \"{fn_name}\"));
+        let bin_path = dir.join(\"{fn_name}\");
+        std::fs::write(&src_path, cpp_source).expect(\"failed to write generated C++\");
+
+        let cxx = std::env::var(\"CXX\").unwrap_or_else(|_| \"c++\".to_string());
+        let mut cmd = std::process::Command::new(cxx);
+        cmd.arg(&src_path).arg(\"-o\").arg(&bin_path);
+        for flag in flags.iter() {{
+            cmd.arg(flag);
+        }}
+        let compiled = cmd.status().expect(\"failed to invoke C++ compiler\");
+        assert!(compiled.success(), \"C++ compiler failed for {{:?}}\", src_path);
+
+        let output = std::process::Command::new(&bin_path)
+            .output()
+            .expect(\"failed to run compiled binary\");
+        let stdout = vec![String::from_utf8_lossy(&output.stdout).to_string()];
+        // The compiled binary has no interpreter-style trailing `result`
+        // value, only stdout and an exit code, so this stays empty.
+        let result = \"\".to_string();
+        {expectation}
+    }}
+}}",
+        fn_name = fn_name,
+        opts = opts,
+        expectation = expectation,
+    )
+}
+
+// Emits a third `#[test]` per example that lowers the module with the LLVM
+// backend, assembles the `.ll` with `clang`, and checks the resulting binary
+// actually runs to completion. This is the smoke test the LLVM backend never
+// had: it's what would have caught `define` blocks nested inside other
+// `define`s, or a user-function call lowered against an SSA register nobody
+// assigned. Skipped unless `TAKO_ENABLE_LLVM_TESTS=1`, matching
+// `build_cpp_diff_test`'s reasoning (dev machines without `clang` on PATH
+// shouldn't fail the suite over it).
+fn build_llvm_smoke_test(fn_name: &str, opts: &str) -> String {
+    if std::env::var("TAKO_ENABLE_LLVM_TESTS").as_deref() != Ok("1") {
+        return "".to_string();
+    }
+    format!(
+        "
+#[test]
+fn {fn_name}_runs_under_llvm_backend() {{
+    let topts = TestOptions::from_str(\"{opts}\").expect(\"Couldn't read test options\");
+    let opts = topts.opts;
+    let mut db = DB::default();
+    db.set_options(opts);
+    for f in db.options().files.iter() {{
+        let module = db.module_name(f.to_owned());
+        let (llvm_ir, _flags) = crate::to_cpp::generate(
+            crate::to_cpp::Backend::Llvm,
+            &db,
+            &module,
+            &crate::externs::ExternRegistry::default(),
+        ).expect(\"codegen failed\");
+
+        let dir = std::env::temp_dir();
+        let ir_path = dir.join(format!(\"{{}}.ll\", \"{fn_name}\"));
+        let bin_path = dir.join(format!(\"{{}}_llvm\", \"{fn_name}\"));
+        std::fs::write(&ir_path, &llvm_ir).expect(\"failed to write generated IR\");
+
+        let clang = std::env::var(\"CLANG\").unwrap_or_else(|_| \"clang\".to_string());
+        let compiled = std::process::Command::new(clang)
+            .arg(&ir_path)
+            .arg(\"-o\")
+            .arg(&bin_path)
+            .status()
+            .expect(\"failed to invoke clang on generated IR\");
+        assert!(compiled.success(), \"clang failed to assemble {{:?}}:\\n{{}}\", ir_path, llvm_ir);
+
+        let status = std::process::Command::new(&bin_path)
+            .status()
+            .expect(\"failed to run LLVM-compiled binary\");
+        assert!(status.success(), \"LLVM-compiled binary exited non-zero for {{:?}}\", ir_path);
+    }}
+}}",
+        fn_name = fn_name,
+        opts = opts,
+    )
+}
+
 fn files_from(path: &str) -> Vec<String> {
     let mut params: Vec<String> = vec![];
     visit_dirs(Path::new(path), &mut |filename| {
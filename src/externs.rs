@@ -1,53 +1,87 @@
 use derivative::Derivative;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
-use crate::ast::{Info, Prim::*};
+use crate::ast::{Apply, BinOp, Err as ErrNode, Info, Let, Path, Prim::*, Sym, Table, UnOp, Visitor};
 use crate::database::Compiler;
+use crate::errors::TError;
 use crate::interpreter::{prim_add_strs, prim_pow, Res};
+// `Variadic` is the wildcard parameter type this module's variadic externs
+// (`print`) declare and `Extern::is_variadic` checks for; it's defined in `types.rs`
+// alongside `Function`/`Value`/`Variable`, not in this file.
 use crate::types::{unit, Type, Type::*, str_type, number_type};
 
 use crate::{map, str_map};
 
 pub type FuncImpl = Box<dyn Fn(&dyn Compiler, Vec<&dyn Fn() -> Res>, Info) -> Res>;
 
-pub fn get_implementation(name: String) -> Option<FuncImpl> {
-    match name.as_str() {
-        "print" => Some(Box::new(|_, args, info| {
-            let val = args[0]()?;
-            match val {
-                Str(s, _) => print!("{}", s),
-                s => print!("{:?}", s),
-            };
-            Ok(I32(0, info))
-        })),
-        "++" => Some(Box::new(|_, args, info| {
-            prim_add_strs(&args[0]()?, &args[1]()?, info)
-        })),
-        "^" => Some(Box::new(|_, args, info| {
-            prim_pow(&args[0]()?, &args[1]()?, info)
-        })),
-        "argc" => Some(Box::new(|db, _, info| {
-            Ok(I32(db.options().interpreter_args.len() as i32, info))
-        })),
-        "argv" => Some(Box::new(|db, args, info| {
-            use crate::errors::TError;
-            match args[0]()? {
-                I32(ind, _) => Ok(Str(
-                    db.options().interpreter_args[ind as usize].clone(),
-                    info,
-                )),
-                value => Err(TError::TypeMismatch(
-                    "Expected index to be of type i32".to_string(),
-                    Box::new(value),
-                    info,
-                )),
-            }
-        })),
-        _ => None,
+// Looks up the interpreter implementation for a builtin by way of
+// `registry`'s merged table (host registrations plus built-ins), instead of
+// the hand-written match this used to be or the built-ins alone. Kept as a
+// free function since most call sites just want the closure and don't care
+// about the rest of the `Extern`.
+pub fn get_implementation(name: String, registry: &ExternRegistry) -> Option<Rc<FuncImpl>> {
+    registry.externs().remove(&name).and_then(|extern_def| extern_def.imp)
+}
+
+// Resolves an extern's interpreter closure for a call site with `arg_count`
+// arguments, checking `registry`'s host registrations as well as the
+// built-ins (see `ExternRegistry::resolve`): an exact arity match wins, and
+// a variadic extern accepts any arg count since its closure folds over
+// however many args it's handed.
+pub fn get_implementation_for_arity(
+    name: &str,
+    arg_count: usize,
+    registry: &ExternRegistry,
+) -> Option<Rc<FuncImpl>> {
+    registry.resolve(name, arg_count).and_then(|extern_def| extern_def.imp)
+}
+
+// Re-attaches native `imp` closures to externs that came back from `serde`
+// with none (closures can't serialize, see `Extern::imp`), matching each
+// overload by name and arity against `live` (typically a host's current
+// native registrations merged with the built-ins). Used by
+// `ExternRegistry::restore`, which is also the reference for how `live`
+// should be assembled. Errors if a saved session references a name/arity
+// `live` doesn't have, so reloading a session can't silently turn a call
+// into a no-op.
+pub fn rebind_implementations(
+    snapshot: HashMap<String, Vec<Extern>>,
+    live: &HashMap<String, Vec<Extern>>,
+) -> Result<HashMap<String, Vec<Extern>>, TError> {
+    let mut rebound = HashMap::with_capacity(snapshot.len());
+    for (name, overloads) in snapshot {
+        let mut resolved = Vec::with_capacity(overloads.len());
+        for mut extern_def in overloads {
+            let arity = extern_def.arity();
+            let imp = live
+                .get(&name)
+                .and_then(|candidates| candidates.iter().find(|c| c.arity() == arity))
+                .and_then(|c| c.imp.clone())
+                .ok_or_else(|| TError::UnknownExtern(name.clone()))?;
+            extern_def.imp = Some(imp);
+            resolved.push(extern_def);
+        }
+        rebound.insert(name, resolved);
     }
+    Ok(rebound)
+}
+
+// A native lowering for an `Extern` that emits LLVM IR directly instead of
+// C++ text, so a host can produce an object file or JIT without a C++
+// toolchain. `declares` holds whatever the snippet needs forward-declared
+// (e.g. `declare double @llvm.pow.f64(double, double)`), and `ir` is the
+// intrinsic/function name to `call`: the LLVM backend emits it as `@{ir}`,
+// so a full instruction snippet is not supported here.
+#[derive(Derivative, Serialize, Deserialize)]
+#[derivative(PartialEq, Eq, Clone, Debug)]
+pub struct LlvmExtern {
+    pub declares: Vec<String>,
+    pub ir: String,
 }
 
-#[derive(Derivative)]
+#[derive(Derivative, Serialize, Deserialize)]
 #[derivative(PartialEq, Eq, Clone, Debug)]
 pub struct Extern {
     pub name: String,
@@ -55,8 +89,23 @@ pub struct Extern {
     pub cpp_includes: String,
     pub cpp_code: String,
     pub cpp_arg_processor: String,
+    // The C++ operator `to_cpp` folds a variadic call's arguments over
+    // (e.g. `"<<"` so `print(a, b)` becomes `std::cout << a << b`), or `""`
+    // for a fixed-arity extern, which is just called normally instead.
+    pub cpp_variadic_join: String,
     pub cpp_flags: String,
+    pub llvm: Option<LlvmExtern>,
     pub ty: Type,
+    // The interpreter closure for this builtin, declared alongside its
+    // backend lowerings so `print`/`++`/`^`/... only exist in one place and
+    // can't drift between the interpreter and codegen tables. `Rc` (rather
+    // than plain `Box`) is what lets `Extern` stay `Clone`. Closures can't
+    // serialize, so a saved session carries `None` here and relies on
+    // `rebind_implementations`/`ExternRegistry::restore` to reattach the
+    // live implementation by name on load.
+    #[derivative(PartialEq = "ignore", Debug = "ignore")]
+    #[serde(skip)]
+    pub imp: Option<Rc<FuncImpl>>,
 }
 
 pub fn get_externs() -> HashMap<String, Extern> {
@@ -65,15 +114,31 @@ pub fn get_externs() -> HashMap<String, Extern> {
             name: "print".to_string(),
             operator: None,
             cpp_includes: "#include <iostream>".to_string(),
-            cpp_code: "std::cout << ".to_string(),
+            cpp_code: "std::cout".to_string(),
             cpp_arg_processor: "".to_string(),
+            cpp_variadic_join: "<<".to_string(),
             cpp_flags: "".to_string(),
+            llvm: Some(LlvmExtern {
+                declares: vec!["declare i32 @printf(i8*, ...)".to_string()],
+                ir: "printf".to_string(),
+            }),
             ty: Function {
                 results: map!{"it".to_string() => Value(unit())},
-                arguments: map!{"it" => str_type()},
-                intros: map!(),
+                // A single `Variadic` slot, so `print` takes any number of
+                // `Display`-constrained arguments instead of exactly one str.
+                arguments: map!{"it" => Variadic(Box::new(Variable("item".to_string())))},
+                intros: str_map!("item" => Variable("Display".to_string())),
                 effects: vec!["stdio".to_string()],
             },
+            imp: Some(Rc::new(Box::new(|_, args, info| {
+                for arg in args.iter() {
+                    match arg()? {
+                        Str(s, _) => print!("{}", s),
+                        s => print!("{:?}", s),
+                    };
+                }
+                Ok(I32(0, info))
+            }))),
         },
         Extern {
             name: "++".to_string(),
@@ -94,13 +159,21 @@ string to_string(const bool& t){
             .to_string(),
             cpp_code: "+".to_string(),
             cpp_arg_processor: "std::to_string".to_string(),
+            cpp_variadic_join: "".to_string(),
             cpp_flags: "".to_string(),
+            llvm: Some(LlvmExtern {
+                declares: vec!["declare i8* @tako_add_strs(i8*, i8*)".to_string()],
+                ir: "tako_add_strs".to_string(),
+            }),
             ty: Function {
                 intros: str_map!("a" => Variable("Display".to_string()), "b" => Variable("Display".to_string())),
-zsxc            results: str_map!("it" => Value(str_type())),
+                results: str_map!("it" => Value(str_type())),
                 arguments: str_map!("left" => Variable("a".to_string()), "right" => Variable("b".to_string())),
                 effects: vec![],
             },
+            imp: Some(Rc::new(Box::new(|_, args, info| {
+                prim_add_strs(&args[0]()?, &args[1]()?, info)
+            }))),
         },
         Extern {
             name: "^".to_string(),
@@ -108,13 +181,21 @@ zsxc            results: str_map!("it" => Value(str_type())),
             cpp_includes: "#include <cmath>".to_string(),
             cpp_code: "pow".to_string(),
             cpp_arg_processor: "".to_string(),
+            cpp_variadic_join: "".to_string(),
             cpp_flags: "-lm".to_string(),
+            llvm: Some(LlvmExtern {
+                declares: vec!["declare double @llvm.pow.f64(double, double)".to_string()],
+                ir: "llvm.pow.f64".to_string(),
+            }),
             ty: Function {
                 intros: str_map!("a" => Variable("Number".to_string()), "b" => Variable("Number".to_string())),
                 results: str_map!("it" => Variable("a".to_string())),
                 arguments: str_map!("left" => Variable("a".to_string()), "right" => Variable("b".to_string())),
                 effects: vec![],
             },
+            imp: Some(Rc::new(Box::new(|_, args, info| {
+                prim_pow(&args[0]()?, &args[1]()?, info)
+            }))),
         },
         Extern {
             name: "argc".to_string(),
@@ -122,8 +203,13 @@ zsxc            results: str_map!("it" => Value(str_type())),
             cpp_includes: "".to_string(),
             cpp_code: "argc".to_string(),
             cpp_arg_processor: "".to_string(),
+            cpp_variadic_join: "".to_string(),
             cpp_flags: "".to_string(),
+            llvm: None, // argc/argv are only meaningful behind `main`'s C++ entry point for now.
             ty: Value(number_type()),
+            imp: Some(Rc::new(Box::new(|db, _, info| {
+                Ok(I32(db.options().interpreter_args.len() as i32, info))
+            }))),
         },
         Extern {
             name: "argv".to_string(),
@@ -131,13 +217,29 @@ zsxc            results: str_map!("it" => Value(str_type())),
             cpp_includes: "".to_string(),
             cpp_code: "([&argv](const int x){return argv[x];})".to_string(),
             cpp_arg_processor: "".to_string(),
+            cpp_variadic_join: "".to_string(),
             cpp_flags: "".to_string(),
+            llvm: None, // Same reasoning as argc: no LLVM lowering until the LLVM backend drives its own `main`.
             ty: Function {
                 results: str_map!("it" => Value(str_type())),
                 intros: map!(),
                 arguments: map!("it".to_string() => Value(number_type())),
                 effects: vec![],
             },
+            imp: Some(Rc::new(Box::new(|db, args, info| {
+                use crate::errors::TError;
+                match args[0]()? {
+                    I32(ind, _) => Ok(Str(
+                        db.options().interpreter_args[ind as usize].clone(),
+                        info,
+                    )),
+                    value => Err(TError::TypeMismatch(
+                        "Expected index to be of type i32".to_string(),
+                        Box::new(value),
+                        info,
+                    )),
+                }
+            }))),
         },
     ];
     let mut extern_map: HashMap<String, Extern> = map!();
@@ -146,3 +248,479 @@ zsxc            results: str_map!("it" => Value(str_type())),
     }
     extern_map
 }
+
+// A host-side table of natively registered externs, for embedding Tako
+// without forking the crate to edit `get_externs`. A name can carry more
+// than one registration (overloaded by arity, see `resolve`).
+#[derive(Default)]
+pub struct ExternRegistry {
+    externs: HashMap<String, Vec<Extern>>,
+}
+
+impl ExternRegistry {
+    // Registers a plain function extern, e.g. `registry.register_fn("sqrt",
+    // ty, |_, args, info| ...)`. Adds an overload if `name` is already registered.
+    pub fn register_fn(&mut self, name: &str, ty: Type, imp: FuncImpl) {
+        self.register(name, ty, None, imp);
+    }
+
+    // Like `register_fn`, but also an operator with the given
+    // (binding power, is_right_assoc), e.g. for a new infix like `<=>`.
+    pub fn register_op(
+        &mut self,
+        name: &str,
+        ty: Type,
+        binding_power: i32,
+        right_assoc: bool,
+        imp: FuncImpl,
+    ) {
+        self.register(name, ty, Some((binding_power, right_assoc)), imp);
+    }
+
+    fn register(&mut self, name: &str, ty: Type, operator: Option<(i32, bool)>, imp: FuncImpl) {
+        self.externs.entry(name.to_string()).or_default().push(Extern {
+            name: name.to_string(),
+            operator,
+            cpp_includes: "".to_string(),
+            cpp_code: "".to_string(),
+            cpp_arg_processor: "".to_string(),
+            cpp_variadic_join: "".to_string(),
+            cpp_flags: "".to_string(),
+            llvm: None,
+            ty,
+            imp: Some(Rc::new(imp)),
+        });
+    }
+
+    // Picks the overload registered under `name` matching a call with
+    // `arg_count` arguments: exact arity wins, else the first variadic
+    // overload. Built-ins are considered after host registrations.
+    pub fn resolve(&self, name: &str, arg_count: usize) -> Option<Extern> {
+        let mut candidates = self.externs.get(name).cloned().unwrap_or_default();
+        if let Some(builtin) = get_externs().remove(name) {
+            candidates.push(builtin);
+        }
+        candidates
+            .iter()
+            .find(|extern_def| extern_def.arity() == Some(arg_count))
+            .or_else(|| candidates.iter().find(|extern_def| extern_def.is_variadic()))
+            .cloned()
+    }
+
+    // The built-ins merged with whatever this host has registered, host
+    // registrations taking priority. Use `resolve` instead for arity-aware
+    // dispatch between several overloads of the same name.
+    pub fn externs(&self) -> HashMap<String, Extern> {
+        let mut all = get_externs();
+        for (name, overloads) in self.externs.iter() {
+            if let Some(extern_def) = overloads.last() {
+                all.insert(name.clone(), extern_def.clone());
+            }
+        }
+        all
+    }
+
+    // A serializable snapshot of every host-registered extern, closures
+    // stripped (see `Extern::imp`). Built-ins aren't included. Pair with
+    // `restore`.
+    pub fn snapshot(&self) -> HashMap<String, Vec<Extern>> {
+        self.externs
+            .iter()
+            .map(|(name, overloads)| {
+                let stripped = overloads
+                    .iter()
+                    .cloned()
+                    .map(|mut extern_def| {
+                        extern_def.imp = None;
+                        extern_def
+                    })
+                    .collect();
+                (name.clone(), stripped)
+            })
+            .collect()
+    }
+
+    // Rebuilds a registry from a `snapshot`, reattaching each extern's
+    // native closure from `self` or a matching built-in. Errors if the
+    // snapshot references a name/arity neither has.
+    pub fn restore(&self, snapshot: HashMap<String, Vec<Extern>>) -> Result<Self, TError> {
+        let mut live = self.externs.clone();
+        for (name, extern_def) in get_externs() {
+            live.entry(name).or_default().push(extern_def);
+        }
+        let externs = rebind_implementations(snapshot, &live)?;
+        Ok(ExternRegistry { externs })
+    }
+}
+
+impl Extern {
+    // The effects declared on this extern's type, or none for a plain value
+    // (e.g. `argc`), which can't carry an `effects` list at all.
+    pub fn effects(&self) -> &[String] {
+        match &self.ty {
+            Function { effects, .. } => effects,
+            _ => &[],
+        }
+    }
+
+    // The number of parameters this extern's signature declares, or `None`
+    // for a plain value (e.g. `argc`), which isn't callable at all. Used to
+    // pick between same-named overloads by arity.
+    pub fn arity(&self) -> Option<usize> {
+        match &self.ty {
+            Function { arguments, .. } => Some(arguments.len()),
+            _ => None,
+        }
+    }
+
+    // True if this extern's signature has a `Variadic` parameter, so it can
+    // be called with any number of trailing arguments of that type (e.g.
+    // `print(a, b, c)`).
+    pub fn is_variadic(&self) -> bool {
+        match &self.ty {
+            Function { arguments, .. } => {
+                arguments.values().any(|ty| matches!(ty, Variadic(_)))
+            }
+            _ => false,
+        }
+    }
+}
+
+// The set of effect names (`stdio`, `fs`, `net`, ...) a host allows a
+// program to perform. `All` is the default, unsandboxed behaviour.
+// Serializable so `Session` can persist it (see `Repl::save`/`Repl::load`).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectSet {
+    All,
+    Only(HashSet<String>),
+}
+
+impl Default for EffectSet {
+    fn default() -> Self {
+        EffectSet::All
+    }
+}
+
+impl EffectSet {
+    pub fn all() -> Self {
+        EffectSet::All
+    }
+
+    pub fn none() -> Self {
+        EffectSet::Only(HashSet::new())
+    }
+
+    pub fn only<I: IntoIterator<Item = S>, S: Into<String>>(effects: I) -> Self {
+        EffectSet::Only(effects.into_iter().map(Into::into).collect())
+    }
+
+    pub fn allows(&self, effect: &str) -> bool {
+        match self {
+            EffectSet::All => true,
+            EffectSet::Only(granted) => granted.contains(effect),
+        }
+    }
+}
+
+// The first effect `name`'s extern needs that isn't in `granted`, or `None`
+// (including when `name` isn't an extern, e.g. a user-defined function).
+// Checks `registry`'s host registrations as well as the built-ins, so a
+// natively registered extern is gated by `granted` too.
+pub fn first_disallowed_effect(
+    name: &str,
+    granted: &EffectSet,
+    registry: &ExternRegistry,
+) -> Option<String> {
+    registry.externs().get(name).and_then(|extern_def| {
+        extern_def
+            .effects()
+            .iter()
+            .find(|effect| !granted.allows(effect))
+            .cloned()
+    })
+}
+
+type EffectsRes = Result<(), TError>;
+type EffectsState = Table;
+type EffectsOut = HashSet<String>;
+
+// Walks the AST collecting the effects used by every extern it calls,
+// failing fast the moment one isn't in the granted `EffectSet`.
+pub struct EffectsChecker<'a> {
+    granted: &'a EffectSet,
+    registry: &'a ExternRegistry,
+    used: HashSet<String>,
+}
+
+impl<'a> EffectsChecker<'a> {
+    pub fn new(granted: &'a EffectSet, registry: &'a ExternRegistry) -> Self {
+        Self {
+            granted,
+            registry,
+            used: HashSet::new(),
+        }
+    }
+
+    fn require(&mut self, name: &str, info: Info) -> EffectsRes {
+        if let Some(effect) = first_disallowed_effect(name, self.granted, self.registry) {
+            return Err(TError::EffectNotGranted(name.to_string(), effect, info));
+        }
+        if let Some(extern_def) = self.registry.externs().get(name) {
+            self.used.extend(extern_def.effects().iter().cloned());
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Visitor<EffectsState, (), EffectsOut, Path> for EffectsChecker<'a> {
+    fn visit_root(&mut self, db: &dyn Compiler, module: &Path) -> Result<EffectsOut, TError> {
+        let root = db.look_up_definitions(module.clone())?;
+        let mut table = root.table;
+        self.visit(db, &mut table, &root.ast)?;
+        Ok(self.used.clone())
+    }
+
+    fn visit_sym(&mut self, _db: &dyn Compiler, _state: &mut EffectsState, expr: &Sym) -> EffectsRes {
+        self.require(&expr.name, expr.get_info())
+    }
+
+    fn visit_prim(&mut self, db: &dyn Compiler, state: &mut EffectsState, expr: &Prim) -> EffectsRes {
+        if let Lambda(node) = expr {
+            return self.visit(db, state, node);
+        }
+        Ok(())
+    }
+
+    fn visit_apply(&mut self, db: &dyn Compiler, state: &mut EffectsState, expr: &Apply) -> EffectsRes {
+        self.visit(db, state, &expr.inner)?;
+        for arg in expr.args.iter() {
+            self.visit(db, state, &arg.value)?;
+        }
+        Ok(())
+    }
+
+    fn visit_let(&mut self, db: &dyn Compiler, state: &mut EffectsState, expr: &Let) -> EffectsRes {
+        self.visit(db, state, &expr.value)
+    }
+
+    fn visit_un_op(&mut self, db: &dyn Compiler, state: &mut EffectsState, expr: &UnOp) -> EffectsRes {
+        self.require(&expr.name, expr.get_info())?;
+        self.visit(db, state, &expr.inner)
+    }
+
+    fn visit_bin_op(&mut self, db: &dyn Compiler, state: &mut EffectsState, expr: &BinOp) -> EffectsRes {
+        self.require(&expr.name, expr.get_info())?;
+        self.visit(db, state, &expr.left)?;
+        self.visit(db, state, &expr.right)
+    }
+
+    fn handle_error(&mut self, _db: &dyn Compiler, _state: &mut EffectsState, expr: &ErrNode) -> EffectsRes {
+        Err(TError::FailedParse(expr.msg.clone(), expr.get_info()))
+    }
+}
+
+// Checks `module`'s transitive extern effects against `granted`, returning
+// the effects actually used or the first disallowed one. `registry`'s host
+// registrations are checked alongside the built-ins.
+pub fn check_effects(
+    db: &dyn Compiler,
+    module: &Path,
+    granted: &EffectSet,
+    registry: &ExternRegistry,
+) -> Result<HashSet<String>, TError> {
+    EffectsChecker::new(granted, registry).visit_root(db, module)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every extern that emits a C++ body must also carry an interpreter
+    // implementation, so the two backends can never silently drift apart
+    // the way `print`/`++`/`^` used to when they were declared twice.
+    #[test]
+    fn every_extern_with_cpp_code_has_an_interpreter_impl() {
+        for (name, extern_def) in get_externs() {
+            if !extern_def.cpp_code.is_empty() {
+                assert!(
+                    extern_def.imp.is_some(),
+                    "extern {:?} emits C++ but has no interpreter implementation",
+                    name
+                );
+            }
+        }
+    }
+
+    // `print` declares `stdio` as its one effect, so it's a convenient stand-in
+    // for checking that `EffectSet` actually gates a call rather than just
+    // bookkeeping the field.
+    #[test]
+    fn sandboxed_effect_set_rejects_stdio_until_it_is_granted() {
+        let registry = ExternRegistry::default();
+        assert_eq!(
+            first_disallowed_effect("print", &EffectSet::none(), &registry),
+            Some("stdio".to_string())
+        );
+        assert_eq!(
+            first_disallowed_effect("print", &EffectSet::only(vec!["stdio"]), &registry),
+            None
+        );
+        assert_eq!(
+            first_disallowed_effect("print", &EffectSet::all(), &registry),
+            None
+        );
+    }
+
+    // An operator with no declared effects (`++`) is never blocked, and an
+    // unknown name (a user-defined function, not an extern) has nothing to
+    // check either.
+    #[test]
+    fn pure_externs_and_unknown_names_are_never_blocked() {
+        let registry = ExternRegistry::default();
+        assert_eq!(
+            first_disallowed_effect("++", &EffectSet::none(), &registry),
+            None
+        );
+        assert_eq!(
+            first_disallowed_effect("some_user_fn", &EffectSet::none(), &registry),
+            None
+        );
+    }
+
+    // A function registered natively through `ExternRegistry::register_fn`
+    // is gated by `granted` exactly like a built-in with the same effects --
+    // the embedding use case `ExternRegistry` exists for shouldn't be a way
+    // to bypass the sandbox it's checked alongside.
+    #[test]
+    fn a_natively_registered_extern_is_checked_against_granted() {
+        let mut registry = ExternRegistry::default();
+        registry.register_fn(
+            "read_file",
+            Function {
+                intros: map!(),
+                results: str_map!("it" => Value(str_type())),
+                arguments: str_map!("path" => Value(str_type())),
+                effects: vec!["fs".to_string()],
+            },
+            Box::new(|_, args, info| args[0]().map(|_| I32(0, info))),
+        );
+        assert_eq!(
+            first_disallowed_effect("read_file", &EffectSet::none(), &registry),
+            Some("fs".to_string())
+        );
+        assert_eq!(
+            first_disallowed_effect("read_file", &EffectSet::only(vec!["fs"]), &registry),
+            None
+        );
+    }
+
+    // `print` takes a single variadic slot, not one fixed argument, so it
+    // should report an arity of one but still flag itself as variadic.
+    #[test]
+    fn print_is_variadic() {
+        let print = get_externs().remove("print").unwrap();
+        assert_eq!(print.arity(), Some(1));
+        assert!(print.is_variadic());
+        assert!(!get_externs().remove("++").unwrap().is_variadic());
+    }
+
+    // Two `register_fn` calls under the same name add overloads rather than
+    // replacing each other, and `resolve` picks between them by arity.
+    #[test]
+    fn registering_the_same_name_twice_adds_an_overload() {
+        let mut registry = ExternRegistry::default();
+        registry.register_fn(
+            "area",
+            Function {
+                intros: map!(),
+                results: str_map!("it" => Value(number_type())),
+                arguments: str_map!("side" => Value(number_type())),
+                effects: vec![],
+            },
+            Box::new(|_, args, _| args[0]()),
+        );
+        registry.register_fn(
+            "area",
+            Function {
+                intros: map!(),
+                results: str_map!("it" => Value(number_type())),
+                arguments: str_map!(
+                    "width" => Value(number_type()),
+                    "height" => Value(number_type())
+                ),
+                effects: vec![],
+            },
+            Box::new(|_, args, _| args[0]()),
+        );
+
+        assert_eq!(registry.resolve("area", 1).unwrap().arity(), Some(1));
+        assert_eq!(registry.resolve("area", 2).unwrap().arity(), Some(2));
+        assert!(registry.resolve("area", 3).is_none());
+    }
+
+    fn registry_with_one_native_fn() -> ExternRegistry {
+        let mut registry = ExternRegistry::default();
+        registry.register_fn(
+            "double",
+            Function {
+                intros: map!(),
+                results: str_map!("it" => Value(number_type())),
+                arguments: str_map!("it" => Value(number_type())),
+                effects: vec![],
+            },
+            Box::new(|_, args, _| args[0]()),
+        );
+        registry
+    }
+
+    // A snapshot strips the native closure (it can't serialize), and
+    // `restore` reattaches it by name/arity from the registry doing the
+    // restoring — the same shape a fresh process's startup registration
+    // would take before loading a saved session.
+    #[test]
+    fn snapshot_then_restore_round_trips_through_json_and_rebinds_the_closure() {
+        let registry = registry_with_one_native_fn();
+        let snapshot = registry.snapshot();
+        assert!(snapshot["double"][0].imp.is_none());
+
+        let json = serde_json::to_string(&snapshot).expect("snapshot should serialize");
+        let reloaded: HashMap<String, Vec<Extern>> =
+            serde_json::from_str(&json).expect("snapshot should deserialize");
+
+        let restored = registry.restore(reloaded).expect("restore should succeed");
+        assert!(restored.externs.get("double").unwrap()[0].imp.is_some());
+    }
+
+    // If the process doing the restoring never registered the name a saved
+    // session references, that's a broken session, not a silent no-op.
+    #[test]
+    fn restoring_an_unregistered_name_is_an_error() {
+        let registry = ExternRegistry::default();
+        let mut snapshot = HashMap::new();
+        snapshot.insert(
+            "double".to_string(),
+            vec![Extern {
+                name: "double".to_string(),
+                operator: None,
+                cpp_includes: "".to_string(),
+                cpp_code: "".to_string(),
+                cpp_arg_processor: "".to_string(),
+                cpp_variadic_join: "".to_string(),
+                cpp_flags: "".to_string(),
+                llvm: None,
+                ty: Function {
+                    intros: map!(),
+                    results: str_map!("it" => Value(number_type())),
+                    arguments: str_map!("it" => Value(number_type())),
+                    effects: vec![],
+                },
+                imp: None,
+            }],
+        );
+
+        assert!(matches!(
+            registry.restore(snapshot),
+            Err(TError::UnknownExtern(name)) if name == "double"
+        ));
+    }
+}
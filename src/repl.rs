@@ -0,0 +1,312 @@
+use crate::ast::*;
+use crate::database::{Compiler, DB};
+use crate::errors::TError;
+use crate::externs::{check_effects, EffectSet, Extern, ExternRegistry, FuncImpl};
+use crate::to_cpp::{generate, Backend};
+use crate::types::Type;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// A serializable snapshot of a `Repl` session: every top-level entry that
+// parsed successfully, in order (replaying them through a fresh `feed`
+// rebuilds `table`/`last_node`, since `Table`/`Node` themselves don't
+// serialize), whatever externs the host registered beyond the built-ins
+// (see `ExternRegistry::snapshot`), and the `EffectSet` the session was
+// granted, so a sandboxed session comes back sandboxed rather than
+// defaulting to `EffectSet::All` on reload. A host writes this to disk with
+// whatever format it likes (`serde_json`, `bincode`, ...) and reloads it
+// into a fresh process with `Repl::load`.
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    history: Vec<String>,
+    externs: HashMap<String, Vec<Extern>>,
+    granted: EffectSet,
+}
+
+// A cancellation handle an embedding host can use to bound an in-flight
+// evaluation. `cancel()` sets the shared flag immediately; `arm_timeout`
+// (driven by `Repl::eval_with_timeout`) also starts a timer thread that sets
+// it once a deadline passes. The interpreter's reduction loop is expected to
+// call `check()` between reductions and unwind with `TError::Interrupted`
+// the moment it's set, so a runaway program gets bounded without the host
+// having to kill the process.
+#[derive(Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: Arc::new((Mutex::new(false), Condvar::new())),
+        }
+    }
+}
+
+impl CancellationToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Cancels immediately and wakes any timer thread waiting on the
+    // deadline, so cancelling early doesn't have to wait out the timeout.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        let (fired, deadline) = &*self.deadline;
+        *fired.lock().unwrap() = true;
+        deadline.notify_all();
+    }
+
+    // Checked between reductions; turns a cancelled token into a typed
+    // error instead of letting evaluation run on.
+    pub fn check(&self) -> Result<(), TError> {
+        if self.is_cancelled() {
+            return Err(TError::Interrupted);
+        }
+        Ok(())
+    }
+
+    // Spawns a timer thread that cancels this token once `timeout` elapses,
+    // unless `cancel()` fires first.
+    fn arm_timeout(&self, timeout: Duration) {
+        let token = self.clone();
+        thread::spawn(move || {
+            let (fired, deadline) = &*token.deadline;
+            let guard = fired.lock().unwrap();
+            let (_guard, result) = deadline.wait_timeout(guard, timeout).unwrap();
+            if result.timed_out() {
+                token.cancel();
+            }
+        });
+    }
+}
+
+// Interactive front-end that drives the same `DB`/`Compiler` and interpreter
+// as `work`, but incrementally: each accepted entry's `Let` bindings stay in
+// the session `table` so later lines can reference earlier definitions.
+pub struct Repl {
+    db: DB,
+    table: Table,
+    module: Path,
+    buffer: String,
+    last_node: Option<Node>,
+    granted: EffectSet,
+    token: CancellationToken,
+    history: Vec<String>,
+    // Host-registered externs, consulted by `eval`/`show_generated` and the
+    // effect sandbox alongside the built-ins (see `register_fn`). Owned here
+    // rather than passed in per call, so a registration made through this
+    // `Repl` is visible everywhere it evaluates or generates code, not just
+    // when `save`/`load` snapshot it.
+    registry: ExternRegistry,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        let db = DB::default();
+        let module = db.module_name("<repl>".to_string());
+        Self {
+            db,
+            table: Table::default(),
+            module,
+            buffer: String::new(),
+            last_node: None,
+            granted: EffectSet::default(),
+            token: CancellationToken::default(),
+            history: Vec::new(),
+            registry: ExternRegistry::default(),
+        }
+    }
+}
+
+impl Repl {
+    // A REPL that only allows the given effects, e.g. `Repl::sandboxed
+    // (EffectSet::none())` for an untrusted script with no `stdio`/`fs`/
+    // `net` access at all. Checked fresh before every `eval` so it also
+    // catches effects introduced by a later line, not just the first one.
+    pub fn sandboxed(granted: EffectSet) -> Self {
+        Self {
+            granted,
+            ..Self::default()
+        }
+    }
+
+    // Registers a native function extern so this `Repl`'s evaluation,
+    // codegen, and effect sandbox all see it, e.g. `repl.register_fn("sqrt",
+    // ty, |_, args, info| ...)`. See `ExternRegistry::register_fn`.
+    pub fn register_fn(&mut self, name: &str, ty: Type, imp: FuncImpl) {
+        self.registry.register_fn(name, ty, imp);
+    }
+
+    // Like `register_fn`, but also an operator with the given
+    // (binding power, is_right_assoc). See `ExternRegistry::register_op`.
+    pub fn register_op(
+        &mut self,
+        name: &str,
+        ty: Type,
+        binding_power: i32,
+        right_assoc: bool,
+        imp: FuncImpl,
+    ) {
+        self.registry.register_op(name, ty, binding_power, right_assoc, imp);
+    }
+
+    // The cancellation handle for whatever evaluation is currently (or next)
+    // in flight; an embedder can call `.cancel()` on it from another thread
+    // to stop a runaway program without killing the process.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+
+    // Evaluates `line` the same way `feed` would, but bounds it to `timeout`
+    // wall-clock time: a timer thread flips a fresh cancellation token once
+    // the deadline passes, and the interpreter's reduction loop unwinds with
+    // `TError::Interrupted` the next time it checks. Evaluation runs on the
+    // calling thread, so the token is returned only after `line` has finished
+    // (or been interrupted); cancelling it does not cut this call short.
+    pub fn eval_with_timeout(&mut self, line: &str, timeout: Duration) -> CancellationToken {
+        self.token = CancellationToken::default();
+        self.token.arm_timeout(timeout);
+        self.feed(line);
+        self.token.clone()
+    }
+
+    // Snapshots the entries accepted so far plus this `Repl`'s own
+    // host-registered externs and granted `EffectSet`, for a caller to
+    // serialize (`serde_json::to_writer`, ...) and resume later with
+    // `Repl::load`.
+    pub fn save(&self) -> Session {
+        Session {
+            history: self.history.clone(),
+            externs: self.registry.snapshot(),
+            granted: self.granted.clone(),
+        }
+    }
+
+    // Rebuilds a `Repl` from a saved `Session`: reattaches `session`'s
+    // externs to `live`'s native closures (erroring if one is no longer
+    // registered), restores `session`'s granted `EffectSet`, and replays the
+    // recorded history through a fresh REPL to rebuild `table`/`last_node`.
+    // Restoring `granted` before replay matters because `eval` re-checks it
+    // on every replayed line, not just new ones, so a sandboxed session
+    // comes back exercising the same restrictions it was saved under.
+    pub fn load(session: Session, live: &ExternRegistry) -> Result<Self, TError> {
+        let registry = live.restore(session.externs)?;
+        let mut repl = Self::sandboxed(session.granted);
+        repl.registry = registry;
+        for entry in &session.history {
+            repl.feed(entry);
+        }
+        Ok(repl)
+    }
+
+    pub fn run(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        loop {
+            print!("{}", self.prompt());
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if stdin.read_line(&mut line)? == 0 {
+                break; // EOF (Ctrl-D).
+            }
+            let line = line.trim_end_matches('\n');
+            match line.trim() {
+                ":q" | ":quit" => break,
+                ":c" => {
+                    self.show_generated(Backend::Cpp);
+                    continue;
+                }
+                ":llvm" => {
+                    self.show_generated(Backend::Llvm);
+                    continue;
+                }
+                _ => {}
+            }
+            self.feed(line);
+        }
+        Ok(())
+    }
+
+    // `"tako> "` for a fresh entry, `"...   "` (a continuation prompt) while
+    // `buffer` holds an input that hasn't parsed cleanly yet.
+    fn prompt(&self) -> &'static str {
+        if self.buffer.is_empty() {
+            "tako> "
+        } else {
+            "...   "
+        }
+    }
+
+    fn feed(&mut self, line: &str) {
+        if self.buffer.is_empty() {
+            self.buffer = line.to_string();
+        } else {
+            self.buffer.push('\n');
+            self.buffer.push_str(line);
+        }
+        match self.db.parse_str(self.module.clone(), &self.buffer) {
+            Ok(node) => {
+                self.history.push(self.buffer.clone());
+                self.buffer.clear();
+                self.eval(node);
+            }
+            Err(err) if is_incomplete(&err) => {
+                // Keep buffering: unbalanced braces/parens or a trailing
+                // binary operator (`;`, `+`, ...) just means more is coming.
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                self.buffer.clear();
+            }
+        }
+    }
+
+    fn eval(&mut self, node: Node) {
+        self.last_node = Some(node.clone());
+        if let Node::LetNode(ref let_node) = node {
+            self.table.insert(let_node.name.clone(), node.clone());
+        }
+        if let Err(err) = check_effects(&self.db, &self.module, &self.granted, &self.registry) {
+            eprintln!("{}", err);
+            return;
+        }
+        if let Err(err) = self.token.check() {
+            eprintln!("{}", err);
+            return;
+        }
+        // Threads `self.token` into the reduction loop itself (rather than
+        // just checking it here, up front) so a timeout armed by
+        // `eval_with_timeout` can unwind a long-running or infinite program
+        // mid-evaluation, not only reject it before it starts. Also threads
+        // `self.registry` so a call to a host-registered extern actually
+        // dispatches to its native closure instead of failing as unknown.
+        match self.db.interpret(&node, &mut self.table, &self.token, &self.registry) {
+            Ok(value) => println!("{:?}", value),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+
+    fn show_generated(&mut self, backend: Backend) {
+        match &self.last_node {
+            Some(_) => match generate(backend, &self.db, &self.module, &self.registry) {
+                Ok((code, _flags)) => println!("{}", code),
+                Err(err) => eprintln!("{}", err),
+            },
+            None => eprintln!("Nothing entered yet."),
+        }
+    }
+}
+
+fn is_incomplete(err: &TError) -> bool {
+    // Only `UnexpectedEof` means "there's more coming" (unbalanced braces/
+    // parens, a trailing binary operator, ...). A `ParseError` is a genuine
+    // syntax error and must be reported, not swallowed into another prompt.
+    matches!(err, TError::UnexpectedEof(..))
+}
@@ -1,13 +1,71 @@
 use crate::ast::*;
+use crate::externs::{Extern, ExternRegistry};
+use crate::to_llvm::LlvmGenerator;
+use crate::types::Type;
 use crate::{database::Compiler, errors::TError};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+// Selects which native backend `work` lowers the AST to. `Cpp` emits C++ text
+// and shells out to a system compiler (the long-standing path); `Llvm` emits
+// `.ll` IR so a user can get an object/executable without a C++ toolchain.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    Cpp,
+    Llvm,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpp" => Ok(Backend::Cpp),
+            "llvm" => Ok(Backend::Llvm),
+            other => Err(format!("Unknown backend {:?}, expected cpp or llvm", other)),
+        }
+    }
+}
+
+// Lowers `module` with whichever backend was requested, producing the
+// generated source plus any linker flags it needs. `registry`'s merged
+// extern table (host registrations plus built-ins) is what `visit_sym`
+// consults for both backends, so a natively registered extern gets the same
+// codegen treatment as a built-in one.
+pub fn generate(
+    backend: Backend,
+    db: &dyn Compiler,
+    module: &Path,
+    registry: &ExternRegistry,
+) -> Result<(String, HashSet<String>), TError> {
+    match backend {
+        Backend::Cpp => CodeGenerator::with_externs(registry.externs()).visit_root(db, module),
+        Backend::Llvm => LlvmGenerator::with_externs(registry.externs()).visit_root(db, module),
+    }
+}
 
 // Walks the AST compiling it to wasm.
 #[derive(Default)]
 pub struct CodeGenerator {
     functions: Vec<Code>,
     includes: HashSet<String>,
+    // `expr -> join` for every variadic extern's callee emitted so far (e.g.
+    // `"std::cout" -> "<<"` for `print`), so `visit_apply` can recognize any
+    // variadic call and fold its arguments with `join` instead of the usual
+    // parenthesized, comma-separated call. Populated by `visit_sym`.
+    variadic_joins: HashMap<String, String>,
     pub flags: HashSet<String>,
+    // The externs `visit_sym` looks up calls against, built from `generate`'s
+    // `registry` so a natively registered extern is visible here too, not
+    // just the built-ins.
+    externs: HashMap<String, Extern>,
+}
+
+impl CodeGenerator {
+    fn with_externs(externs: HashMap<String, Extern>) -> Self {
+        Self {
+            externs,
+            ..Self::default()
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -103,6 +161,35 @@ impl Code {
     }
 }
 
+// Maps an inferred tako type to a concrete C++ type, the way a typed-value
+// compiler carries its value/type environment into lowering. Falls back to
+// `auto` only when inference genuinely couldn't pin a type down, so we keep
+// the old permissive behaviour for anything we don't yet understand.
+fn type_to_cpp(ty: &Type) -> String {
+    match ty {
+        Type::Value(inner) => type_to_cpp(inner),
+        Type::Function { results, .. } => match results.values().next() {
+            Some(result) => format!("std::function<{}()>", type_to_cpp(result)),
+            None => "auto".to_string(),
+        },
+        Type::Variable(name) => match name.as_str() {
+            "Number" => "int".to_string(),
+            "Bool" => "bool".to_string(),
+            "String" | "Display" => "std::string".to_string(),
+            _ => "auto".to_string(),
+        },
+        _ => "auto".to_string(),
+    }
+}
+
+// Looks up the type inferred for `info`, if any, and renders it as C++.
+fn inferred_cpp_type(info: &Info) -> String {
+    info.ty
+        .as_ref()
+        .map(type_to_cpp)
+        .unwrap_or_else(|| "auto".to_string())
+}
+
 pub fn make_name(def: Vec<Symbol>) -> String {
     let def_n: Vec<String> = def.iter().map(|n| n.clone().to_name()).collect();
     def_n.join("_")
@@ -179,6 +266,19 @@ type State = Table;
 type Out = (String, HashSet<String>);
 
 impl CodeGenerator {
+    // Like `inferred_cpp_type`, but also pulls in whatever `#include` the
+    // resulting C++ type needs (`std::string`/`std::function`).
+    fn cpp_type_for(&mut self, info: &Info) -> String {
+        let ty = inferred_cpp_type(info);
+        if ty.contains("std::string") {
+            self.includes.insert("#include <string>".to_string());
+        }
+        if ty.contains("std::function") {
+            self.includes.insert("#include <functional>".to_string());
+        }
+        ty
+    }
+
     fn build_call1(&mut self, before: &str, inner: Code) -> Code {
         inner.with_expr(&|exp| Code::Expr(format!("{}({})", before, exp)))
     }
@@ -220,6 +320,9 @@ impl Visitor<State, Code, Out, Path> for CodeGenerator {
                 args: vec!["int argc".to_string(), "char* argv[]".to_string()],
                 body,
                 lambda: false,
+                // `main`'s signature is fixed by the C++ standard, unlike
+                // every other `Func`, whose return type now comes from
+                // `inferred_cpp_type` instead of being hardcoded.
                 return_type: "int".to_string(),
             },
             thing => panic!("main must be a Func {:?}", thing),
@@ -264,9 +367,18 @@ impl Visitor<State, Code, Out, Path> for CodeGenerator {
                 .defined_at
                 .expect("Could not find definition for symbol"),
         );
-        if name == "print" {
-            self.includes.insert("#include <iostream>".to_string());
-            return Ok(Code::Expr("std::cout << ".to_owned()));
+        // Any variadic extern (not just the built-in `print`) folds its call
+        // with `cpp_variadic_join` instead of a parenthesized, comma call --
+        // see the matching check in `visit_apply`.
+        if let Some(extern_def) = self.externs.get(&name) {
+            if extern_def.is_variadic() && !extern_def.cpp_variadic_join.is_empty() {
+                if !extern_def.cpp_includes.is_empty() {
+                    self.includes.insert(extern_def.cpp_includes.clone());
+                }
+                self.variadic_joins
+                    .insert(extern_def.cpp_code.clone(), extern_def.cpp_variadic_join.clone());
+                return Ok(Code::Expr(extern_def.cpp_code.clone()));
+            }
         }
         if name == "argc" {
             return Ok(Code::Expr("argc".to_owned()));
@@ -302,7 +414,8 @@ impl Visitor<State, Code, Out, Path> for CodeGenerator {
                 let mut arg_names: Vec<String> = vec![];
                 for lambda_arg in args.iter() {
                     arg_names.push(format!(
-                        "const auto {}",
+                        "{} {}",
+                        self.cpp_type_for(&lambda_arg.get_info()),
                         pretty_print_block(self.visit_sym(db, state, lambda_arg)?, "")
                     ));
                 }
@@ -315,6 +428,15 @@ impl Visitor<State, Code, Out, Path> for CodeGenerator {
         // TODO: require label is none.
         let arg_str = arg_exprs.join(", ");
         match val {
+            // A variadic extern (e.g. `print`) folds its arguments with its
+            // own join operator (`std::cout << a << b`) instead of the usual
+            // parenthesized call, which would otherwise reduce multiple args
+            // to C++'s comma operator.
+            Code::Expr(expr) if self.variadic_joins.contains_key(&expr) => {
+                let join = &self.variadic_joins[&expr];
+                let folded = arg_exprs.join(&format!(" {} ", join));
+                Ok(Code::Expr(format!("{} {} {}", expr, join, folded)))
+            }
             Code::Expr(expr) => {
                 let with_args = format!("{}({})", expr, arg_str);
                 Ok(Code::Expr(with_args))
@@ -351,6 +473,7 @@ impl Visitor<State, Code, Out, Path> for CodeGenerator {
                 .defined_at
                 .expect("Could not find definition for let"),
         );
+        let return_type = self.cpp_type_for(&expr.get_info());
         let body = self.visit(db, state, &expr.value)?;
         if let Some(args) = &expr.args {
             let body = body.with_expr(&|exp| Code::Statement(format!("return {}", exp)));
@@ -358,7 +481,8 @@ impl Visitor<State, Code, Out, Path> for CodeGenerator {
                 .iter()
                 .map(|s| {
                     format!(
-                        "const auto {}",
+                        "{} {}",
+                        self.cpp_type_for(&s.get_info()),
                         make_name(
                             s.get_info()
                                 .defined_at
@@ -371,14 +495,14 @@ impl Visitor<State, Code, Out, Path> for CodeGenerator {
             let node = Code::Func {
                 name,
                 args,
-                return_type: "int".to_string(),
+                return_type,
                 body: Box::new(body),
                 lambda: true,
             };
 
             return Ok(node);
         }
-        Ok(body.with_expr(&|x| Code::Statement(format!("const auto {} = {}", name, x))))
+        Ok(body.with_expr(&|x| Code::Statement(format!("{} {} = {}", return_type, name, x))))
     }
 
     fn visit_un_op(&mut self, db: &dyn Compiler, state: &mut State, expr: &UnOp) -> Res {
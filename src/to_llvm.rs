@@ -0,0 +1,421 @@
+use crate::ast::*;
+use crate::externs::Extern;
+use crate::types::Type;
+use crate::{database::Compiler, errors::TError};
+use std::collections::{HashMap, HashSet};
+
+// Walks the AST compiling it to LLVM IR, mirroring the traversal in `to_cpp` but
+// lowering straight to SSA instructions instead of C++ text. Kept as plain
+// textual IR (rather than `inkwell`'s builder API) so this module has no extra
+// native dependency: the output is valid `.ll` that `llc`/`clang` accept directly.
+#[derive(Default)]
+pub struct LlvmGenerator {
+    functions: Vec<Ir>,
+    declares: HashSet<String>,
+    next_reg: u32,
+    // The externs `visit_sym`/`visit_bin_op` look up calls against, built
+    // from `to_cpp::generate`'s `registry` so a natively registered extern
+    // is visible here too, not just the built-ins.
+    externs: HashMap<String, Extern>,
+}
+
+impl LlvmGenerator {
+    pub fn with_externs(externs: HashMap<String, Extern>) -> Self {
+        Self {
+            externs,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Ir {
+    Empty,
+    // A fully evaluated SSA value, e.g. `i32 4` or `%t3`.
+    Value(String),
+    Block(Vec<Ir>),
+    Instruction(String),
+    If {
+        condition: Box<Ir>,
+        then: Box<Ir>,
+        then_else: Box<Ir>,
+    },
+    Func {
+        name: String,
+        args: Vec<String>, // already-formatted `<ty> %name` pairs
+        return_type: String,
+        body: Box<Ir>,
+    },
+}
+
+impl Ir {
+    fn with_value(self: Ir, f: &dyn Fn(String) -> Ir) -> Ir {
+        match self {
+            Ir::Empty => Ir::Empty,
+            Ir::Value(value) => f(value),
+            Ir::Block(mut statements) => {
+                let last = statements.pop().unwrap();
+                statements.push(last.with_value(f));
+                Ir::Block(statements)
+            }
+            Ir::Instruction(line) => Ir::Instruction(line),
+            Ir::If {
+                condition,
+                then,
+                then_else,
+            } => Ir::If {
+                condition,
+                then,
+                then_else,
+            },
+            Ir::Func {
+                name,
+                args,
+                mut body,
+                return_type,
+            } => {
+                body = Box::new(body.with_value(f));
+                Ir::Func {
+                    name,
+                    args,
+                    body,
+                    return_type,
+                }
+            }
+        }
+    }
+
+    fn merge(self: Ir, other: Ir) -> Ir {
+        match (self, other) {
+            (Ir::Empty, right) => right,
+            (left, Ir::Empty) => left,
+            (Ir::Block(mut left), Ir::Block(right)) => {
+                left.extend(right);
+                Ir::Block(left)
+            }
+            (Ir::Block(mut left), right) => {
+                left.push(right);
+                Ir::Block(left)
+            }
+            (left, right) => Ir::Block(vec![left, right]),
+        }
+    }
+}
+
+fn pretty_print_ir(src: Ir) -> String {
+    match src {
+        Ir::Block(statements) => statements
+            .into_iter()
+            .map(pretty_print_ir)
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Ir::Value(value) => value,
+        Ir::Instruction(line) => format!("  {}", line),
+        Ir::Empty => "".to_string(),
+        Ir::If {
+            condition,
+            then,
+            then_else,
+        } => format!(
+            "{}\n{}\n{}",
+            pretty_print_ir(*condition),
+            pretty_print_ir(*then),
+            pretty_print_ir(*then_else),
+        ),
+        Ir::Func {
+            name,
+            args,
+            return_type,
+            body,
+        } => format!(
+            "define {} @{}({}) {{\n{}\n}}",
+            return_type,
+            name,
+            args.join(", "),
+            pretty_print_ir(*body),
+        ),
+    }
+}
+
+type Res = Result<Ir, TError>;
+type State = Table;
+type Out = (String, HashSet<String>);
+
+impl LlvmGenerator {
+    fn fresh_reg(&mut self) -> String {
+        let reg = format!("%t{}", self.next_reg);
+        self.next_reg += 1;
+        reg
+    }
+
+    fn build_bin_instr(&mut self, op: &str, left: Ir, right: Ir) -> Ir {
+        left.with_value(&|left_val| {
+            right.clone().with_value(&|right_val| {
+                let reg = self.fresh_reg();
+                Ir::Block(vec![
+                    Ir::Instruction(format!("{} = {} i32 {}, {}", reg, op, left_val, right_val)),
+                    Ir::Value(reg.clone()),
+                ])
+            })
+        })
+    }
+
+    // Like `build_bin_instr`, but for a `double`-typed call (currently just
+    // `pow`): converts both `i32` operands to `double`, calls `callee`, then
+    // converts the result back, since the rest of this backend is `i32`-only.
+    fn build_pow_instr(&mut self, callee: &str, left: Ir, right: Ir) -> Ir {
+        left.with_value(&|left_val| {
+            right.clone().with_value(&|right_val| {
+                let left_d = self.fresh_reg();
+                let right_d = self.fresh_reg();
+                let call_reg = self.fresh_reg();
+                let result = self.fresh_reg();
+                Ir::Block(vec![
+                    Ir::Instruction(format!("{} = sitofp {} to double", left_d, left_val)),
+                    Ir::Instruction(format!("{} = sitofp {} to double", right_d, right_val)),
+                    Ir::Instruction(format!(
+                        "{} = call double @{}(double {}, double {})",
+                        call_reg, callee, left_d, right_d
+                    )),
+                    Ir::Instruction(format!("{} = fptosi double {} to i32", result, call_reg)),
+                    Ir::Value(result),
+                ])
+            })
+        })
+    }
+}
+
+impl Visitor<State, Ir, Out, Path> for LlvmGenerator {
+    fn visit_root(&mut self, db: &dyn Compiler, module: &Path) -> Result<Out, TError> {
+        let root = db.look_up_definitions(module.clone())?;
+        let mut main_info = root.ast.get_info();
+        let mut main_at = module.clone();
+        main_at.push(Symbol::new("main".to_string()));
+        main_info.defined_at = Some(main_at);
+        let main_let = Let {
+            info: main_info,
+            name: "main".to_string(),
+            value: Box::new(root.ast.clone()),
+            args: Some(vec![]),
+        };
+        let mut table = root.table; // TODO: Shouldn't be mut
+        // `visit_let` hoists every function-shaped `Let` (including this
+        // synthetic `main`) into `self.functions` rather than returning it
+        // directly, so pull the one it just pushed back out to rename it.
+        self.visit_let(db, &mut table, &main_let)?;
+        let main = match self.functions.pop() {
+            Some(Ir::Func {
+                name: _,
+                args: _,
+                body,
+                return_type: _,
+            }) => Ir::Func {
+                name: "main".to_string(),
+                args: vec![],
+                body,
+                return_type: "i32".to_string(),
+            },
+            thing => panic!("main must be a Func {:?}", thing),
+        };
+
+        let mut code = "".to_string();
+        let mut declares: Vec<&String> = self.declares.iter().collect();
+        declares.sort();
+        for decl in declares.iter() {
+            code = format!("{}{}\n", code, decl);
+        }
+
+        self.functions.push(main);
+        for func in self.functions.iter().cloned() {
+            code = format!("{}\n{}\n", code, pretty_print_ir(func));
+        }
+        Ok((code, HashSet::new())) // The LLVM backend links natively, no extra flags needed.
+    }
+
+    fn visit_sym(&mut self, _db: &dyn Compiler, _state: &mut State, expr: &Sym) -> Res {
+        let name = make_name(
+            expr.get_info()
+                .defined_at
+                .expect("Could not find definition for symbol"),
+        );
+        // Externs carry their own LLVM lowering (see `Extern::llvm`), so a
+        // builtin like `print` maps straight onto its declared intrinsic
+        // instead of being special-cased here.
+        if let Some(extern_def) = self.externs.get(&name) {
+            if let Some(llvm) = &extern_def.llvm {
+                for declare in &llvm.declares {
+                    self.declares.insert(declare.clone());
+                }
+                return Ok(Ir::Value(format!("@{}", llvm.ir)));
+            }
+        }
+        // A user-defined function is hoisted to its own top-level `define`
+        // by `visit_let`, so calling it needs the global `@name` symbol, not
+        // a local SSA register (which is never assigned for it).
+        if matches!(expr.get_info().ty, Some(Type::Function { .. })) {
+            return Ok(Ir::Value(format!("@{}", name)));
+        }
+        Ok(Ir::Value(format!("%{}", name)))
+    }
+
+    fn visit_prim(&mut self, db: &dyn Compiler, state: &mut State, expr: &Prim) -> Res {
+        use Prim::*;
+        match expr {
+            I32(n, _) => Ok(Ir::Value(format!("i32 {}", n))),
+            Bool(true, _) => Ok(Ir::Value("i1 1".to_string())),
+            Bool(false, _) => Ok(Ir::Value("i1 0".to_string())),
+            Str(s, _) => {
+                let reg = self.fresh_reg();
+                Ok(Ir::Block(vec![
+                    Ir::Instruction(format!(
+                        "{} = private unnamed_addr constant [{} x i8] c\"{}\\00\"",
+                        reg,
+                        s.len() + 1,
+                        s
+                    )),
+                    Ir::Value(reg),
+                ]))
+            }
+            Lambda(node) => self.visit(db, state, node),
+        }
+    }
+
+    fn visit_apply(&mut self, db: &dyn Compiler, state: &mut State, expr: &Apply) -> Res {
+        let val = self.visit(db, state, &expr.inner)?;
+        let mut arg_vals = vec![];
+        for arg in expr.args.iter() {
+            let body = self.visit(db, state, &arg.value)?;
+            arg_vals.push(body);
+        }
+        match val {
+            Ir::Value(callee) => {
+                let mut arg_strs = vec![];
+                // Each arg's instructions (e.g. a string literal's constant
+                // decl, a `+`/`^` result's arithmetic) must still land in the
+                // output before the `call`, or the call references a
+                // register nothing ever defined. `with_value` only swaps out
+                // the trailing value, so merge what's left back into
+                // `prelude` instead of discarding it.
+                let mut prelude = Ir::Empty;
+                for arg in arg_vals {
+                    let mut collected = String::new();
+                    let arg = arg.with_value(&|v| {
+                        collected = v;
+                        Ir::Empty
+                    });
+                    prelude = prelude.merge(arg);
+                    arg_strs.push(collected);
+                }
+                let reg = self.fresh_reg();
+                let call = Ir::Instruction(format!(
+                    "{} = call i32 {}({})",
+                    reg,
+                    callee,
+                    arg_strs.join(", ")
+                ));
+                Ok(prelude.merge(call).merge(Ir::Value(reg)))
+            }
+            _ => panic!("Don't know how to call a non-value in LLVM IR"),
+        }
+    }
+
+    fn visit_let(&mut self, db: &dyn Compiler, state: &mut State, expr: &Let) -> Res {
+        let filename = expr
+            .get_info()
+            .loc
+            .expect("cannot find symbol location")
+            .filename
+            .expect("cannot find symbol file location");
+        let context = db.module_name(filename);
+        let path = expr.get_info().defined_at.expect("Undefined symbol")[context.len()..].to_vec();
+        let uses = db
+            .find_symbol_uses(context.clone(), path.clone())?
+            .unwrap_or_else(|| panic!("couldn't find {:?} {:?}", context.clone(), path.clone()));
+        if uses.is_empty() {
+            return Ok(Ir::Empty);
+        }
+        let name = make_name(
+            expr.get_info()
+                .defined_at
+                .expect("Could not find definition for let"),
+        );
+        let body = self.visit(db, state, &expr.value)?;
+        if let Some(args) = &expr.args {
+            let body = body.with_value(&|v| Ir::Instruction(format!("ret {}", v)));
+            let args: Vec<String> = args
+                .iter()
+                .map(|s| {
+                    format!(
+                        "i32 %{}",
+                        make_name(
+                            s.get_info()
+                                .defined_at
+                                .expect("Could not find definition for let argument"),
+                        )
+                    )
+                })
+                .collect();
+            // LLVM functions can't nest inside another `define` the way the
+            // C++ lambdas `to_cpp.rs` emits for the same shape can, so hoist
+            // this one to its own top-level entry instead of merging it into
+            // whatever body is visiting this `Let`.
+            self.functions.push(Ir::Func {
+                name,
+                args,
+                return_type: "i32".to_string(),
+                body: Box::new(body),
+            });
+            // Nothing to merge into the enclosing block: callers reach this
+            // function by name via `visit_sym`, not through this `Ir`.
+            return Ok(Ir::Empty);
+        }
+        Ok(body)
+    }
+
+    fn visit_un_op(&mut self, db: &dyn Compiler, state: &mut State, expr: &UnOp) -> Res {
+        let code = self.visit(db, state, &expr.inner)?;
+        let info = expr.get_info();
+        let res = match expr.name.as_str() {
+            "-" => self.build_bin_instr("sub", Ir::Value("i32 0".to_string()), code),
+            "+" => code,
+            op => return Err(TError::UnknownPrefixOperator(op.to_string(), info)),
+        };
+        Ok(res)
+    }
+
+    fn visit_bin_op(&mut self, db: &dyn Compiler, state: &mut State, expr: &BinOp) -> Res {
+        let info = expr.get_info();
+        let left = self.visit(db, state, &expr.left.clone())?;
+        let right = self.visit(db, state, &expr.right.clone())?;
+        let res = match expr.name.as_str() {
+            "+" => self.build_bin_instr("add", left, right),
+            "-" => self.build_bin_instr("sub", left, right),
+            "*" => self.build_bin_instr("mul", left, right),
+            "/" => self.build_bin_instr("sdiv", left, right),
+            "==" => self.build_bin_instr("icmp eq", left, right),
+            "!=" => self.build_bin_instr("icmp ne", left, right),
+            ">" => self.build_bin_instr("icmp sgt", left, right),
+            "<" => self.build_bin_instr("icmp slt", left, right),
+            ">=" => self.build_bin_instr("icmp sge", left, right),
+            "<=" => self.build_bin_instr("icmp sle", left, right),
+            "^" => {
+                let pow = self
+                    .externs
+                    .get("^")
+                    .and_then(|e| e.llvm.clone())
+                    .expect("`^` extern must declare an LLVM lowering");
+                for declare in &pow.declares {
+                    self.declares.insert(declare.clone());
+                }
+                self.build_pow_instr(&pow.ir, left, right)
+            }
+            ";" => return Ok(left.merge(right)),
+            op => return Err(TError::UnknownInfixOperator(op.to_string(), info)),
+        };
+        Ok(res)
+    }
+
+    fn handle_error(&mut self, _db: &dyn Compiler, _state: &mut State, expr: &Err) -> Res {
+        Err(TError::FailedParse(expr.msg.clone(), expr.get_info()))
+    }
+}